@@ -1,76 +1,374 @@
-use std::{io, path::{Path, PathBuf}};
+use std::{io, path::{Path, PathBuf}, pin::Pin};
+use flate2::{Compress, Compression, FlushCompress, Status};
 use tokio::{
     fs,
-    io::AsyncReadExt,
+    io::{AsyncRead, AsyncReadExt},
     sync::mpsc::{channel, Receiver, Sender},
 };
 
+use crate::date::Timestamp;
 use crate::error::Result;
-use crate::zip::{Descriptor, Directory, FileHeader, ToBytes};
+use crate::stream::ZippedReader;
+use crate::zip::{CompressionMethod, Descriptor, Directory, FileHeader, ToBytes};
 
 mod date;
 pub mod error;
+pub mod stream;
 mod zip;
+
+/// A boxed, type-erased asynchronous byte source for an archive entry whose
+/// content doesn't come from the filesystem.
+type BoxAsyncRead = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Default Unix permission bits used on platforms where a file's real mode
+/// can't be read from its metadata.
+const DEFAULT_MODE: u32 = 0o644;
+
+/// Default Unix permission bits for a directory entry with no explicit
+/// mode. Needs the execute bit (unlike `DEFAULT_MODE`) or extractors that
+/// honor the stored mode produce a directory nothing can `cd` into.
+const DEFAULT_DIR_MODE: u32 = 0o755;
+
+/// How close a `Path` entry's uncompressed size may sit below `u32::MAX`
+/// before DEFLATE is forced to reserve the ZIP64 extra field up front.
+/// Incompressible input can make `compressed_size` a few bytes larger than
+/// `uncompressed_size` per stored block, so a size within this margin could
+/// still cross `u32::MAX` once compressed even though `expected_size` didn't.
+const ZIP64_SAFETY_MARGIN: u64 = 1024 * 1024;
+
+#[cfg(unix)]
+fn file_mode(meta: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::MetadataExt;
+    meta.mode()
+}
+
+#[cfg(not(unix))]
+fn file_mode(_meta: &std::fs::Metadata) -> u32 {
+    DEFAULT_MODE
+}
+
+/// A single archive member, either read from the filesystem or from an
+/// arbitrary asynchronous source supplied by the caller.
+pub enum ZipEntry {
+    /// The path its bytes are read from, and the name it is stored under in
+    /// the archive. The two may differ, e.g. when archiving a directory the
+    /// entry is opened at its path under the given root but stored under a
+    /// path relative to that root.
+    Path {
+        open_path: PathBuf,
+        archive_name: String,
+        comment: Option<String>,
+    },
+    /// An entry whose bytes come from an arbitrary `AsyncRead`, described
+    /// up front since it has no filesystem metadata to read them from.
+    Stream {
+        archive_name: String,
+        modified: Timestamp,
+        mode: Option<u32>,
+        comment: Option<String>,
+        reader: BoxAsyncRead,
+    },
+    /// A directory entry: zero-length content, stored under a name ending
+    /// in `/` with the `S_IFDIR` bits OR'd into its mode, so extractors
+    /// recreate the tree (and its permissions) rather than just its files.
+    Directory {
+        archive_name: String,
+        modified: Timestamp,
+        mode: Option<u32>,
+        comment: Option<String>,
+    },
+}
+
+impl ZipEntry {
+    pub fn new(open_path: impl Into<PathBuf>, archive_name: impl Into<String>) -> Self {
+        ZipEntry::Path {
+            open_path: open_path.into(),
+            archive_name: archive_name.into(),
+            comment: None,
+        }
+    }
+
+    /// Describes an entry streamed from `reader` rather than opened from
+    /// disk, e.g. an HTTP response body or an in-memory buffer.
+    pub fn from_reader(
+        archive_name: impl Into<String>,
+        modified: impl Into<Timestamp>,
+        mode: Option<u32>,
+        reader: impl AsyncRead + Send + 'static,
+    ) -> Self {
+        ZipEntry::Stream {
+            archive_name: archive_name.into(),
+            modified: modified.into(),
+            mode,
+            comment: None,
+            reader: Box::pin(reader),
+        }
+    }
+
+    /// Describes a directory entry. `mode` defaults to `0o755` (before the
+    /// `S_IFDIR` bits are OR'd in) when `None`, so extractors that honor
+    /// the stored mode still produce a directory that can be traversed.
+    pub fn new_directory(
+        archive_name: impl Into<String>,
+        modified: impl Into<Timestamp>,
+        mode: Option<u32>,
+    ) -> Self {
+        ZipEntry::Directory {
+            archive_name: archive_name.into(),
+            modified: modified.into(),
+            mode,
+            comment: None,
+        }
+    }
+
+    /// Sets a per-entry comment, written into this entry's central-directory
+    /// record.
+    pub fn with_comment(mut self, comment: impl Into<String>) -> Self {
+        match &mut self {
+            ZipEntry::Path { comment: c, .. }
+            | ZipEntry::Stream { comment: c, .. }
+            | ZipEntry::Directory { comment: c, .. } => {
+                *c = Some(comment.into());
+            }
+        }
+        self
+    }
+}
+
+impl<P: AsRef<Path>> From<P> for ZipEntry {
+    fn from(path: P) -> Self {
+        let archive_name = path.as_ref().as_os_str().to_string_lossy().into_owned();
+        ZipEntry::new(path.as_ref().to_path_buf(), archive_name)
+    }
+}
+
 pub struct Zipper<P> {
-    files: Box<dyn Iterator<Item = P> + Send>,
+    files: Vec<P>,
+    compression: CompressionMethod,
+    comment: Option<String>,
 }
 
 impl<P> Zipper<P>
 where
-    P: AsRef<Path> + Send + Sync + 'static,
+    P: Into<ZipEntry> + Send + 'static,
 
 {
-    
+
     pub fn from_iter<I>(files: I) -> Self
     where
-        I: Iterator<Item = P> + Send + 'static,
+        I: IntoIterator<Item = P>,
     {
         Zipper {
-            files: Box::new(files),
+            files: files.into_iter().collect(),
+            compression: CompressionMethod::default(),
+            comment: None,
+        }
+    }
+
+    /// Sets the compression method used for every entry written by this
+    /// `Zipper`.
+    pub fn with_compression(mut self, compression: CompressionMethod) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the archive-level comment stored in the end-of-central-directory
+    /// record.
+    pub fn with_comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Streams one entry's content, feeding every chunk through `sender`
+    /// while compressing it (if requested) and accumulating its CRC and
+    /// sizes. Works over any `AsyncRead`, so both filesystem files and
+    /// caller-supplied sources share this path.
+    async fn stream_content(
+        mut reader: impl AsyncRead + Unpin,
+        compression: CompressionMethod,
+        pos: &mut u64,
+        sender: &Sender<std::result::Result<Vec<u8>, io::Error>>,
+    ) -> Result<(u64, u64, u32)> {
+        let mut hasher = crc32fast::Hasher::new();
+        let mut uncompressed_size: u64 = 0;
+        let mut compressed_size: u64 = 0;
+
+        async fn send(
+            pos: &mut u64,
+            sender: &Sender<std::result::Result<Vec<u8>, io::Error>>,
+            data: Vec<u8>,
+        ) {
+            *pos += data.len() as u64;
+            sender.send(Ok(data)).await.expect("receiver gone");
+        }
+
+        match compression {
+            CompressionMethod::Store => loop {
+                let mut data = Vec::with_capacity(8 * 1024);
+                let read = reader.read_buf(&mut data).await?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&data);
+                uncompressed_size += data.len() as u64;
+                compressed_size += data.len() as u64;
+                send(pos, sender, data).await;
+            },
+            CompressionMethod::Deflate => {
+                // raw deflate stream, no zlib header/trailer
+                let mut compressor = Compress::new(Compression::default(), false);
+                loop {
+                    let mut data = Vec::with_capacity(8 * 1024);
+                    let read = reader.read_buf(&mut data).await?;
+                    hasher.update(&data);
+                    uncompressed_size += data.len() as u64;
+                    let is_last_chunk = read == 0;
+                    let flush = if is_last_chunk {
+                        FlushCompress::Finish
+                    } else {
+                        FlushCompress::None
+                    };
+
+                    // `compress_vec` only fills the spare capacity it's
+                    // handed and doesn't grow the vec itself, and one call
+                    // isn't guaranteed to consume all of `data` or (on the
+                    // final `Finish` flush) to reach `Status::StreamEnd`.
+                    // Keep feeding it the unconsumed remainder, via a fresh
+                    // output buffer each time, until it has.
+                    let mut offset = 0;
+                    loop {
+                        let mut chunk = Vec::with_capacity(8 * 1024);
+                        let in_before = compressor.total_in();
+                        let out_before = compressor.total_out();
+                        let status = compressor
+                            .compress_vec(&data[offset..], &mut chunk, flush)
+                            .expect("in-memory deflate compression cannot fail");
+                        offset += (compressor.total_in() - in_before) as usize;
+                        compressed_size += compressor.total_out() - out_before;
+                        if !chunk.is_empty() {
+                            send(pos, sender, chunk).await;
+                        }
+
+                        if status == Status::StreamEnd || (!is_last_chunk && offset >= data.len()) {
+                            break;
+                        }
+                    }
+
+                    if is_last_chunk {
+                        break;
+                    }
+                }
+            }
         }
+
+        Ok((uncompressed_size, compressed_size, hasher.finalize()))
     }
 
     async fn main_loop(
-        mut files: Box<dyn Iterator<Item = P> + Send>,
+        files: Vec<P>,
+        compression: CompressionMethod,
+        comment: Option<String>,
         sender: Sender<std::result::Result<Vec<u8>, io::Error>>,
     ) -> Result<()> {
         let mut pos: u64 = 0;
         let mut dir = Directory::new();
 
         macro_rules! send {
-            ($data:ident) => {
-                pos += $data.len() as u64;
-                sender.send(Ok($data)).await.expect("receiver gone");
-            };
+            ($data:expr) => {{
+                let data = $data;
+                pos += data.len() as u64;
+                sender.send(Ok(data)).await.expect("receiver gone");
+            }};
         }
 
-        while let Some(path) = files.next() {
-            let mut f = fs::File::open(&path).await?;
-            let meta = f.metadata().await?;
-            // send header
-            let file_header = FileHeader::new(path, meta.modified()?);
-            let file_header_bytes = file_header.to_bytes()?;
+        for item in files {
+            let entry: ZipEntry = item.into();
             let file_header_offset = pos;
-            send!(file_header_bytes);
 
-            let file_content_offset = pos;
-            let mut hasher = crc32fast::Hasher::new();
-            loop {
-                let mut data = Vec::with_capacity(8 * 1024);
-                let read = f.read_buf(&mut data).await?;
-                if read == 0 {
-                    break;
-                }
-                hasher.update(&data);
-                send!(data);
-            }
+            let (file_header, uncompressed_size, compressed_size, crc, entry_comment, force_zip64) =
+                match entry {
+                    ZipEntry::Path {
+                        open_path,
+                        archive_name,
+                        comment,
+                    } => {
+                        let mut f = fs::File::open(&open_path).await?;
+                        let meta = f.metadata().await?;
+                        let expected_size = meta.len();
+                        // DEFLATE's compressed_size isn't known until the
+                        // content has streamed through and can exceed
+                        // expected_size by a few bytes per block, so a size
+                        // within ZIP64_SAFETY_MARGIN of u32::MAX might still
+                        // cross it once compressed; reserve the zip64 extra
+                        // field up front rather than risk a local
+                        // header/data descriptor width mismatch.
+                        let force_zip64 = compression == CompressionMethod::Deflate
+                            && expected_size > std::u32::MAX as u64 - ZIP64_SAFETY_MARGIN;
+                        let file_header = FileHeader::new(
+                            archive_name,
+                            meta.modified()?,
+                            compression,
+                            if force_zip64 { std::u64::MAX } else { expected_size },
+                            file_mode(&meta),
+                        );
+                        send!(file_header.to_bytes()?);
+
+                        let sizes =
+                            Self::stream_content(&mut f, compression, &mut pos, &sender).await?;
+                        (file_header, sizes.0, sizes.1, sizes.2, comment, force_zip64)
+                    }
+                    ZipEntry::Stream {
+                        archive_name,
+                        modified,
+                        mode,
+                        comment,
+                        reader,
+                    } => {
+                        // the real size isn't known ahead of streaming it, so
+                        // always reserve the ZIP64 extra field in the local
+                        // header (forcing it via `u64::MAX`) rather than
+                        // guessing low and risking a header that undersells
+                        // what the data descriptor ends up needing
+                        let file_header = FileHeader::new(
+                            archive_name,
+                            modified,
+                            compression,
+                            std::u64::MAX,
+                            mode.unwrap_or(DEFAULT_MODE),
+                        );
+                        send!(file_header.to_bytes()?);
+
+                        let sizes =
+                            Self::stream_content(reader, compression, &mut pos, &sender).await?;
+                        (file_header, sizes.0, sizes.1, sizes.2, comment, true)
+                    }
+                    ZipEntry::Directory {
+                        archive_name,
+                        modified,
+                        mode,
+                        comment,
+                    } => {
+                        let file_header = FileHeader::new_directory(
+                            archive_name,
+                            modified,
+                            mode.unwrap_or(DEFAULT_DIR_MODE),
+                        );
+                        send!(file_header.to_bytes()?);
+                        (file_header, 0, 0, 0, comment, false)
+                    }
+                };
+
+            let desc = if force_zip64 {
+                Descriptor::new_zip64(uncompressed_size, compressed_size, crc)
+            } else {
+                Descriptor::new(uncompressed_size, compressed_size, crc)
+            };
+            send!(desc.to_bytes()?);
+            dir.add_entry(file_header, desc, file_header_offset, entry_comment);
+        }
 
-            let file_size = pos - file_content_offset;
-            let crc = hasher.finalize();
-            let desc = Descriptor::new(file_size, crc);
-            let desc_bytes = desc.to_bytes()?;
-            send!(desc_bytes);
-            dir.add_entry(file_header, desc, file_header_offset);
+        if let Some(comment) = comment {
+            dir.set_comment(comment);
         }
         let directory_bytes = dir.finalize(pos)?;
         send!(directory_bytes);
@@ -83,13 +381,20 @@ where
 
         tokio::spawn(async move {
             let sender = s.clone();
-            let res = Zipper::main_loop(self.files, sender).await;
+            let res = Zipper::main_loop(self.files, self.compression, self.comment, sender).await;
             if let Err(e) = res {
                 s.send(Err(e.into())).await.ok();
             }
         });
         r
     }
+
+    /// Same as [`Zipper::zipped_stream`], but wrapped in an `AsyncRead` so
+    /// the archive can be streamed with `tokio::io::copy` or handed to an
+    /// HTTP response body instead of draining the channel by hand.
+    pub fn zipped_reader(self) -> ZippedReader {
+        ZippedReader::new(self.zipped_stream())
+    }
 }
 
 impl Zipper<PathBuf> {
@@ -101,7 +406,95 @@ impl Zipper<PathBuf> {
                 files.push(entry.path())
             }
         }
-        
+
+        Ok(Zipper::from_iter(files.into_iter()))
+    }
+}
+
+type BoxFuture<'a, T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + Send + 'a>>;
+
+fn walk_directory<'a>(
+    root: &'a Path,
+    dir: PathBuf,
+    files: &'a mut Vec<ZipEntry>,
+) -> BoxFuture<'a, io::Result<()>> {
+    Box::pin(async move {
+        let mut dir_listing = fs::read_dir(&dir).await?;
+        while let Some(entry) = dir_listing.next_entry().await? {
+            let path = entry.path();
+            let file_type = entry.file_type().await?;
+            let relative = path
+                .strip_prefix(root)
+                .expect("walked path is always under root");
+            let archive_name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            if file_type.is_dir() {
+                let meta = entry.metadata().await?;
+                files.push(ZipEntry::new_directory(
+                    archive_name,
+                    meta.modified()?,
+                    Some(file_mode(&meta)),
+                ));
+                walk_directory(root, path, files).await?;
+            } else if file_type.is_file() {
+                files.push(ZipEntry::new(path, archive_name));
+            }
+        }
+        Ok(())
+    })
+}
+
+impl Default for Zipper<ZipEntry> {
+    fn default() -> Self {
+        Zipper::from_iter(Vec::new())
+    }
+}
+
+impl Zipper<ZipEntry> {
+    /// Starts an empty archive builder, with entries added one at a time
+    /// via [`Zipper::add_entry`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an entry whose content is streamed from `reader` rather than
+    /// read from the filesystem, e.g. an HTTP response body or an
+    /// in-memory buffer. `mode` defaults to `0o644` when `None`.
+    pub fn add_entry(
+        &mut self,
+        name: impl Into<String>,
+        modified: impl Into<Timestamp>,
+        mode: Option<u32>,
+        reader: impl AsyncRead + Send + 'static,
+    ) -> &mut Self {
+        self.files
+            .push(ZipEntry::from_reader(name, modified, mode, reader));
+        self
+    }
+
+    /// Pushes a fully-built entry, for cases [`Zipper::add_entry`]'s fixed
+    /// parameter list doesn't cover, e.g. a per-entry comment set via
+    /// [`ZipEntry::with_comment`] before the entry is added.
+    pub fn push_entry(&mut self, entry: ZipEntry) -> &mut Self {
+        self.files.push(entry);
+        self
+    }
+
+    /// Like [`Zipper::from_directory`], but walks the whole tree instead of
+    /// just its immediate children. Each entry is stored under its path
+    /// relative to `path` (with `/` separators), so the archive mirrors the
+    /// directory structure instead of flattening it.
+    pub async fn from_directory_recursive(
+        path: impl AsRef<Path>,
+    ) -> std::result::Result<Zipper<ZipEntry>, io::Error> {
+        let root = path.as_ref().to_path_buf();
+        let mut files = vec![];
+        walk_directory(&root, root.clone(), &mut files).await?;
+
         Ok(Zipper::from_iter(files.into_iter()))
     }
 }
@@ -111,39 +504,255 @@ mod tests {
 
     use std::io::{Cursor, Read, Write};
     use crate::error::Result;
-    use super::Zipper;
+    use super::{ZipEntry, Zipper};
     use tokio::io::AsyncReadExt;
     use zip::ZipArchive;
     #[tokio::test]
     async fn test_zip_stream() -> Result<()>{
-        let zipper = Zipper::from_directory("src").await?;
+        // Zip a throwaway fixture directory rather than the crate's own
+        // `src/`, so this test doesn't have to be updated every time a
+        // module file is added or removed.
+        let root = std::env::temp_dir().join(format!("async_zip_stream_test_{}", std::process::id()));
+        tokio::fs::create_dir_all(&root).await.unwrap();
+        let contents: Vec<(&str, &[u8])> = vec![
+            ("one.txt", b"the first fixture file"),
+            ("two.txt", b"the second fixture file"),
+            ("three.txt", b"the third fixture file"),
+        ];
+        for (name, content) in &contents {
+            tokio::fs::write(root.join(name), content).await.unwrap();
+        }
+
+        let zipper = Zipper::from_directory(&root).await?;
         let mut stream = zipper.zipped_stream();
         let mut f = Cursor::new(Vec::<u8>::new());
         while let Some(chunk) = stream.recv().await {
             f.write_all(&(chunk?)).unwrap();
         }
 
-        assert!(f.get_ref().len()>1000);
-
         f.set_position(0);
 
         let mut zip = ZipArchive::new(f).expect("cannot open archive");
-        assert_eq!(zip.len(), 4);
-        for i in 0..zip.len() {
-            let mut file = zip.by_index(i).expect("entry error");
-            println!("Filename: {} {} {:?}", file.name(), file.size(), file.last_modified());
+        assert_eq!(zip.len(), contents.len());
+        for (name, expected) in &contents {
+            let mut file = zip
+                .by_name(root.join(name).to_str().unwrap())
+                .expect("entry error");
             let mut content = vec![];
             file.read_to_end(&mut content).expect("read content error");
+            assert_eq!(&content, expected);
+        }
 
-            let mut tf = tokio::fs::File::open(file.name()).await.expect("cannot open file");
-            let meta = tf.metadata().await.expect("cannot get metadata");
+        tokio::fs::remove_dir_all(&root).await.ok();
 
-            assert_eq!(meta.len(), file.size());
-            let mut tc = vec![];
-            tf.read_to_end(&mut tc).await.expect("cannot read file");
-            assert_eq!(tc, content);
-;        }
+        Ok(())
+    }
 
+    #[tokio::test]
+    async fn test_deflate_round_trip() -> Result<()> {
+        use crate::zip::CompressionMethod;
+
+        let content = "the quick brown fox jumps over the lazy dog\n".repeat(500);
+        let mut zipper = Zipper::new().with_compression(CompressionMethod::Deflate);
+        zipper.add_entry(
+            "fox.txt",
+            std::time::SystemTime::now(),
+            None,
+            Cursor::new(content.clone().into_bytes()),
+        );
+
+        let mut stream = zipper.zipped_stream();
+        let mut f = Cursor::new(Vec::<u8>::new());
+        while let Some(chunk) = stream.recv().await {
+            f.write_all(&(chunk?)).unwrap();
+        }
+
+        // compression should have actually shrunk this highly repetitive text
+        assert!(f.get_ref().len() < content.len());
+
+        f.set_position(0);
+        let mut zip = ZipArchive::new(f).expect("cannot open archive");
+        assert_eq!(zip.len(), 1);
+        let mut file = zip.by_index(0).expect("entry error");
+        let mut extracted = String::new();
+        file.read_to_string(&mut extracted).expect("read content error");
+        assert_eq!(extracted, content);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unix_mode_and_directory_entries() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join(format!("async_zip_mode_test_{}", std::process::id()));
+        tokio::fs::create_dir_all(root.join("sub/empty")).await.unwrap();
+        let file_path = root.join("sub/hello.txt");
+        tokio::fs::write(&file_path, b"hello").await.unwrap();
+        let mut perms = tokio::fs::metadata(&file_path).await.unwrap().permissions();
+        perms.set_mode(0o755);
+        tokio::fs::set_permissions(&file_path, perms).await.unwrap();
+
+        let zipper = Zipper::from_directory_recursive(&root).await?;
+        let mut stream = zipper.zipped_stream();
+        let mut f = Cursor::new(Vec::<u8>::new());
+        while let Some(chunk) = stream.recv().await {
+            f.write_all(&(chunk?)).unwrap();
+        }
+        f.set_position(0);
+
+        let mut zip = ZipArchive::new(f).expect("cannot open archive");
+        let mut names: Vec<String> = (0..zip.len())
+            .map(|i| zip.by_index(i).unwrap().name().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["sub/", "sub/empty/", "sub/hello.txt"]);
+
+        for i in 0..zip.len() {
+            let file = zip.by_index(i).expect("entry error");
+            if file.name() == "sub/hello.txt" {
+                assert_eq!(file.unix_mode().expect("unix mode") & 0o777, 0o755);
+            } else {
+                assert!(file.name().ends_with('/'));
+            }
+        }
+
+        tokio::fs::remove_dir_all(&root).await.ok();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_directory_entry_defaults_to_executable_mode() -> Result<()> {
+        let mut zipper = Zipper::new();
+        zipper.files.push(ZipEntry::new_directory(
+            "no_mode/",
+            std::time::SystemTime::now(),
+            None,
+        ));
+
+        let mut stream = zipper.zipped_stream();
+        let mut f = Cursor::new(Vec::<u8>::new());
+        while let Some(chunk) = stream.recv().await {
+            f.write_all(&(chunk?)).unwrap();
+        }
+        f.set_position(0);
+
+        let mut zip = ZipArchive::new(f).expect("cannot open archive");
+        let file = zip.by_index(0).expect("entry error");
+        assert_eq!(file.unix_mode().expect("unix mode") & 0o777, 0o755);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recursive_nested_relative_paths() -> Result<()> {
+        let root = std::env::temp_dir().join(format!("async_zip_nest_test_{}", std::process::id()));
+        tokio::fs::create_dir_all(root.join("a/b/c")).await.unwrap();
+        tokio::fs::write(root.join("top.txt"), b"top").await.unwrap();
+        tokio::fs::write(root.join("a/mid.txt"), b"mid").await.unwrap();
+        tokio::fs::write(root.join("a/b/c/deep.txt"), b"deep").await.unwrap();
+
+        let zipper = Zipper::from_directory_recursive(&root).await?;
+        let mut stream = zipper.zipped_stream();
+        let mut f = Cursor::new(Vec::<u8>::new());
+        while let Some(chunk) = stream.recv().await {
+            f.write_all(&(chunk?)).unwrap();
+        }
+        f.set_position(0);
+
+        let mut zip = ZipArchive::new(f).expect("cannot open archive");
+        let mut names: Vec<String> = (0..zip.len())
+            .map(|i| zip.by_index(i).unwrap().name().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["a/", "a/b/", "a/b/c/", "a/b/c/deep.txt", "a/mid.txt", "top.txt"]
+        );
+
+        tokio::fs::remove_dir_all(&root).await.ok();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_entry_from_reader() -> Result<()> {
+        let mut zipper = Zipper::new();
+        zipper.add_entry(
+            "buffer.bin",
+            std::time::SystemTime::now(),
+            Some(0o600),
+            Cursor::new(b"in-memory content".to_vec()),
+        );
+
+        let mut stream = zipper.zipped_stream();
+        let mut f = Cursor::new(Vec::<u8>::new());
+        while let Some(chunk) = stream.recv().await {
+            f.write_all(&(chunk?)).unwrap();
+        }
+        f.set_position(0);
+
+        let mut zip = ZipArchive::new(f).expect("cannot open archive");
+        assert_eq!(zip.len(), 1);
+        let mut file = zip.by_index(0).expect("entry error");
+        assert_eq!(file.name(), "buffer.bin");
+        assert_eq!(file.unix_mode().expect("unix mode") & 0o777, 0o600);
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).expect("read content error");
+        assert_eq!(content, b"in-memory content");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_zipped_reader_as_async_read() -> Result<()> {
+        let mut zipper = Zipper::new();
+        zipper.add_entry(
+            "via_reader.txt",
+            std::time::SystemTime::now(),
+            None,
+            Cursor::new(b"streamed via AsyncRead".to_vec()),
+        );
+
+        let mut reader = zipper.zipped_reader();
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+
+        let mut zip = ZipArchive::new(Cursor::new(buf)).expect("cannot open archive");
+        assert_eq!(zip.len(), 1);
+        let mut file = zip.by_index(0).expect("entry error");
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).expect("read content error");
+        assert_eq!(content, b"streamed via AsyncRead");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_archive_and_entry_comments() -> Result<()> {
+        let mut zipper = Zipper::new().with_comment("archive comment");
+        zipper.push_entry(
+            ZipEntry::from_reader(
+                "noted.txt",
+                std::time::SystemTime::now(),
+                None,
+                Cursor::new(b"content".to_vec()),
+            )
+            .with_comment("entry comment"),
+        );
+
+        let mut stream = zipper.zipped_stream();
+        let mut f = Cursor::new(Vec::<u8>::new());
+        while let Some(chunk) = stream.recv().await {
+            f.write_all(&(chunk?)).unwrap();
+        }
+        f.set_position(0);
+
+        let mut zip = ZipArchive::new(f).expect("cannot open archive");
+        assert_eq!(zip.comment(), b"archive comment");
+        let file = zip.by_index(0).expect("entry error");
+        assert_eq!(file.comment(), "entry comment");
 
         Ok(())
     }
@@ -0,0 +1,56 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, Bytes};
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    sync::mpsc::Receiver,
+};
+
+/// Adapts the `Receiver` returned by [`crate::Zipper::zipped_stream`] into a
+/// plain `AsyncRead`, so the archive can be handed directly to things like
+/// `tokio::io::copy` or an `axum`/`hyper` response body instead of the
+/// caller hand-rolling a `recv()` loop.
+pub struct ZippedReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    buffer: Bytes,
+}
+
+impl ZippedReader {
+    pub fn new(receiver: Receiver<io::Result<Vec<u8>>>) -> Self {
+        ZippedReader {
+            receiver,
+            buffer: Bytes::new(),
+        }
+    }
+}
+
+impl AsyncRead for ZippedReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if self.buffer.has_remaining() {
+                let n = std::cmp::min(self.buffer.len(), buf.remaining());
+                buf.put_slice(&self.buffer[..n]);
+                self.buffer.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+
+            match self.receiver.poll_recv(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.buffer = Bytes::from(chunk);
+                    // loop around to serve from the freshly filled buffer
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
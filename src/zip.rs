@@ -1,5 +1,3 @@
-use std::path::Path;
-
 use bytes::{BufMut, BytesMut};
 
 use crate::error::Result;
@@ -9,10 +7,58 @@ const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
 const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x02014b50;
 const CENTRAL_DIRECTORY_END_SIGNATURE: u32 = 0x06054b50;
 const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const ZIP64_EOCD_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_EOCD_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const ZIP64_EXTRA_FIELD_ID: u16 = 0x0001;
 
 const MIN_VERSION: u16 = 20;
+const ZIP64_VERSION: u16 = 45;
 const FLAGS: u16 = 0b0000_1000_0000_1000;
-const COMPRESS_STORE: u16 = 0;
+// "version made by" host byte identifying a Unix-originated archive
+const UNIX_HOST: u16 = 3;
+// S_IFDIR, OR'd into a directory entry's Unix mode
+const S_IFDIR: u32 = 0o040000;
+
+/// Encodes a ZIP64 extended-information extra field carrying `values` in the
+/// fixed order the spec expects. Central-directory entries pass only the
+/// fields that overflowed their 32-bit slot; local headers pass zeroed
+/// size placeholders.
+fn zip64_extra_field(values: &[u64]) -> Vec<u8> {
+    let mut buf = BytesMut::with_capacity(4 + values.len() * 8);
+    buf.put_u16_le(ZIP64_EXTRA_FIELD_ID);
+    buf.put_u16_le((values.len() * 8) as u16);
+    for v in values {
+        buf.put_u64_le(*v);
+    }
+    buf.to_vec()
+}
+
+/// Compression method used to store a single entry's bytes.
+///
+/// Only the subset of the ZIP compression methods that this crate knows how
+/// to write is represented here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionMethod {
+    /// No compression (method `0`).
+    Store,
+    /// DEFLATE (method `8`).
+    Deflate,
+}
+
+impl CompressionMethod {
+    fn code(&self) -> u16 {
+        match self {
+            CompressionMethod::Store => 0,
+            CompressionMethod::Deflate => 8,
+        }
+    }
+}
+
+impl Default for CompressionMethod {
+    fn default() -> Self {
+        CompressionMethod::Store
+    }
+}
 
 pub trait ToBytes {
     fn to_bytes(&self) -> Result<Vec<u8>>;
@@ -21,35 +67,78 @@ pub trait ToBytes {
 pub struct FileHeader {
     file_name: String,
     modified: Timestamp,
+    method: CompressionMethod,
+    /// Size of the entry's uncompressed bytes, known up-front from the
+    /// source's metadata. Real sizes are only ever written to the data
+    /// descriptor that follows the content, but this hints whether the
+    /// entry is large enough to need the ZIP64 extra field reserved here.
+    expected_size: u64,
+    /// Unix permission bits (and, for directories, the `S_IFDIR` file-type
+    /// bits), stored in the upper 16 bits of the external file attributes.
+    mode: u32,
 }
 
 impl FileHeader {
-    pub fn new(path: impl AsRef<Path>, modified: impl Into<Timestamp>) -> Self {
-        let file_name = path
-            .as_ref()
-            .as_os_str()
-            .to_string_lossy()
-            .to_owned()
-            .to_string();
+    /// `name` is the path stored in the archive, which may differ from
+    /// whatever on-disk path the caller read the entry's bytes from (e.g.
+    /// a path relative to an archived directory's root).
+    pub fn new(
+        name: impl Into<String>,
+        modified: impl Into<Timestamp>,
+        method: CompressionMethod,
+        expected_size: u64,
+        mode: u32,
+    ) -> Self {
+        FileHeader {
+            file_name: name.into(),
+            modified: modified.into(),
+            method,
+            expected_size,
+            mode,
+        }
+    }
+
+    /// Builds a zero-length directory entry: the name is suffixed with `/`
+    /// and `mode` gets the `S_IFDIR` file-type bits OR'd in, so extractors
+    /// recreate the tree (and its permissions) rather than just its files.
+    pub fn new_directory(name: impl Into<String>, modified: impl Into<Timestamp>, mode: u32) -> Self {
+        let mut file_name = name.into();
+        if !file_name.ends_with('/') {
+            file_name.push('/');
+        }
         FileHeader {
             file_name,
             modified: modified.into(),
+            method: CompressionMethod::Store,
+            expected_size: 0,
+            mode: mode | S_IFDIR,
         }
     }
 }
 
 impl ToBytes for FileHeader {
     fn to_bytes(&self) -> Result<Vec<u8>> {
-        let mut h = BytesMut::with_capacity(30 + self.file_name.len());
+        let zip64 = self.expected_size > std::u32::MAX as u64;
+        let version = if zip64 { ZIP64_VERSION } else { MIN_VERSION };
+        // real sizes are only known once the content has streamed through,
+        // so reserve zeroed zip64 placeholders that the data descriptor's
+        // 8-byte fields will supersede
+        let extra = if zip64 {
+            zip64_extra_field(&[0, 0])
+        } else {
+            Vec::new()
+        };
+
+        let mut h = BytesMut::with_capacity(30 + self.file_name.len() + extra.len());
 
         // local file header signature
         h.put_u32_le(LOCAL_FILE_HEADER_SIGNATURE);
         // version needed to extract
-        h.put_u16_le(MIN_VERSION);
+        h.put_u16_le(version);
         // general purpose bit flag
         h.put_u16_le(FLAGS);
         // Compression method
-        h.put_u16_le(COMPRESS_STORE);
+        h.put_u16_le(self.method.code());
         // last mod file time and last mod file date
         h.put_u16_le(self.modified.dos_timepart());
         h.put_u16_le(self.modified.dos_datepart()?);
@@ -65,41 +154,78 @@ impl ToBytes for FileHeader {
         }
         h.put_u16_le(self.file_name.as_bytes().len() as u16);
         // extra field length
-        h.put_u16_le(0);
+        h.put_u16_le(extra.len() as u16);
         // file name
         h.put_slice(self.file_name.as_bytes());
+        // extra field
+        h.put_slice(&extra);
 
         Ok(h.to_vec())
     }
 }
 
 pub struct Descriptor {
-    size: u64,
+    uncompressed_size: u64,
+    compressed_size: u64,
     crc: u32,
+    /// Forces the 8-byte ZIP64 field widths even if the actual sizes fit in
+    /// 32 bits. Needed for entries whose local header already committed to
+    /// ZIP64 before the real size was known (e.g. a streamed source with no
+    /// size available up front), so the descriptor's width stays consistent
+    /// with what the local header promised.
+    force_zip64: bool,
+}
+
+impl Descriptor {
+    pub fn new(uncompressed_size: u64, compressed_size: u64, crc: u32) -> Self {
+        Descriptor {
+            uncompressed_size,
+            compressed_size,
+            crc,
+            force_zip64: false,
+        }
+    }
+
+    /// Like [`Descriptor::new`], but always writes the ZIP64 8-byte field
+    /// widths.
+    pub fn new_zip64(uncompressed_size: u64, compressed_size: u64, crc: u32) -> Self {
+        Descriptor {
+            uncompressed_size,
+            compressed_size,
+            crc,
+            force_zip64: true,
+        }
+    }
 }
 
 impl Descriptor {
-    pub fn new(size: u64, crc: u32) -> Self {
-        Descriptor { size, crc }
+    fn is_zip64(&self) -> bool {
+        self.force_zip64
+            || self.uncompressed_size > std::u32::MAX as u64
+            || self.compressed_size > std::u32::MAX as u64
     }
 }
 
 impl ToBytes for Descriptor {
     fn to_bytes(&self) -> Result<Vec<u8>> {
-        let mut d = BytesMut::with_capacity(16);
-
-        if self.size > std::u32::MAX as u64 {
-            return Err(Error::FileTooBig(self.size));
-        }
+        let zip64 = self.is_zip64();
+        let mut d = BytesMut::with_capacity(if zip64 { 24 } else { 16 });
 
         // data_descriptor header signature
         d.put_u32_le(DATA_DESCRIPTOR_SIGNATURE);
         // crc-32
         d.put_u32_le(self.crc);
-        // compressed size
-        d.put_u32_le(self.size as u32);
-        // uncompressed size
-        d.put_u32_le(self.size as u32);
+        if zip64 {
+            // compressed size
+            d.put_u64_le(self.compressed_size);
+            // uncompressed size
+            d.put_u64_le(self.uncompressed_size);
+        } else {
+            // compressed size
+            d.put_u32_le(self.compressed_size as u32);
+            // uncompressed size
+            d.put_u32_le(self.uncompressed_size as u32);
+        }
 
         Ok(d.to_vec())
     }
@@ -109,97 +235,205 @@ pub struct DirectoryEntry {
     header: FileHeader,
     desc: Descriptor,
     offset: u64,
+    comment: Option<String>,
 }
 
 impl DirectoryEntry {
+    /// Fields that overflow a 32-bit slot, in the fixed order the ZIP64
+    /// extra field expects: uncompressed size, compressed size, then the
+    /// local-header offset.
+    fn zip64_overflow_fields(&self) -> Vec<u64> {
+        let mut fields = Vec::new();
+        if self.desc.uncompressed_size > std::u32::MAX as u64 {
+            fields.push(self.desc.uncompressed_size);
+        }
+        if self.desc.compressed_size > std::u32::MAX as u64 {
+            fields.push(self.desc.compressed_size);
+        }
+        if self.offset > std::u32::MAX as u64 {
+            fields.push(self.offset);
+        }
+        fields
+    }
+
+    fn comment_len(&self) -> usize {
+        self.comment.as_ref().map_or(0, |c| c.as_bytes().len())
+    }
+
     fn size(&self) -> u32 {
-        (self.header.file_name.len() + 46) as u32
+        let overflow = self.zip64_overflow_fields();
+        let extra_len = if overflow.is_empty() { 0 } else { 4 + overflow.len() * 8 };
+        (self.header.file_name.len() + 46 + extra_len + self.comment_len()) as u32
     }
 }
 
 impl DirectoryEntry {
     fn add_to_bytes<T: BufMut>(&self, buf: &mut T) -> Result<()> {
+        let uncompressed_overflow = self.desc.uncompressed_size > std::u32::MAX as u64;
+        let compressed_overflow = self.desc.compressed_size > std::u32::MAX as u64;
+        let offset_overflow = self.offset > std::u32::MAX as u64;
+        let overflow = self.zip64_overflow_fields();
+        let zip64 = !overflow.is_empty();
+        let extra = if zip64 {
+            zip64_extra_field(&overflow)
+        } else {
+            Vec::new()
+        };
+        let version = if zip64 { ZIP64_VERSION } else { MIN_VERSION };
+
         // central file header signature
         buf.put_u32_le(CENTRAL_DIRECTORY_HEADER_SIGNATURE);
-        // version made by
-        buf.put_u16_le(MIN_VERSION);
+        // version made by (high byte: Unix host)
+        buf.put_u16_le((UNIX_HOST << 8) | version);
         // version needed to extract
-        buf.put_u16_le(MIN_VERSION);
+        buf.put_u16_le(version);
         // general puprose bit flag
         buf.put_u16_le(FLAGS);
         // compression method
-        buf.put_u16_le(COMPRESS_STORE);
+        buf.put_u16_le(self.header.method.code());
         // last mod file time + date
         buf.put_u16_le(self.header.modified.dos_timepart());
         buf.put_u16_le(self.header.modified.dos_datepart()?);
         // crc-32
         buf.put_u32_le(self.desc.crc);
         // compressed size
-        if self.desc.size > std::u32::MAX as u64 {
-            return Err(Error::FileTooBig(self.desc.size));
-        }
-        buf.put_u32_le(self.desc.size as u32);
+        buf.put_u32_le(if compressed_overflow {
+            std::u32::MAX
+        } else {
+            self.desc.compressed_size as u32
+        });
         // uncompressed size
-        buf.put_u32_le(self.desc.size as u32);
+        buf.put_u32_le(if uncompressed_overflow {
+            std::u32::MAX
+        } else {
+            self.desc.uncompressed_size as u32
+        });
         // file name length
         if self.header.file_name.len() > std::u16::MAX as usize {
             return Err(Error::FileNameTooBig);
         }
         buf.put_u16_le(self.header.file_name.as_bytes().len() as u16);
         // extra field length
-        buf.put_u16_le(0);
+        buf.put_u16_le(extra.len() as u16);
         // file comment length
-        buf.put_u16_le(0);
+        let comment = self.comment.as_deref().unwrap_or("").as_bytes();
+        if comment.len() > std::u16::MAX as usize {
+            return Err(Error::CommentTooBig);
+        }
+        buf.put_u16_le(comment.len() as u16);
         // disk number start
         buf.put_u16_le(0);
         // internal file attributes
         buf.put_u16_le(0);
-        // external file attributes
-        buf.put_u32_le(0);
+        // external file attributes: Unix mode bits in the upper 16 bits
+        buf.put_u32_le(self.header.mode << 16);
         // relative offset of local header
-        if self.offset > std::u32::MAX as u64 {
-            return Err(Error::ArchiveTooBig);
-        }
-        buf.put_u32_le(self.offset as u32);
+        buf.put_u32_le(if offset_overflow {
+            std::u32::MAX
+        } else {
+            self.offset as u32
+        });
         // file name
         buf.put_slice(self.header.file_name.as_bytes());
         // extra field
+        buf.put_slice(&extra);
         // file comment
-        // <none>
+        buf.put_slice(comment);
 
         Ok(())
     }
 }
 
 struct DirectoryEnd {
-    number_of_files: u16,
-    dir_size: u32,
+    number_of_files: u64,
+    dir_size: u64,
     dir_offset: u64,
+    comment: Vec<u8>,
 }
 
 impl DirectoryEnd {
+    fn needs_zip64(&self) -> bool {
+        self.number_of_files > std::u16::MAX as u64
+            || self.dir_size > std::u32::MAX as u64
+            || self.dir_offset > std::u32::MAX as u64
+    }
+
+    /// Writes the ZIP64 end-of-central-directory record and its locator,
+    /// which must immediately precede the ordinary EOCD.
+    fn add_zip64_to_bytes<T: BufMut>(&self, buf: &mut T) {
+        let zip64_eocd_offset = self.dir_offset + self.dir_size;
+
+        // zip64 end of central directory record
+        buf.put_u32_le(ZIP64_EOCD_SIGNATURE);
+        // size of this record, excluding the leading signature + size fields
+        buf.put_u64_le(44);
+        // version made by
+        buf.put_u16_le(ZIP64_VERSION);
+        // version needed to extract
+        buf.put_u16_le(ZIP64_VERSION);
+        // number of this disk
+        buf.put_u32_le(0);
+        // disk with the start of the central directory
+        buf.put_u32_le(0);
+        // total number of entries on this disk
+        buf.put_u64_le(self.number_of_files);
+        // total number of entries
+        buf.put_u64_le(self.number_of_files);
+        // size of the central directory
+        buf.put_u64_le(self.dir_size);
+        // offset of the central directory
+        buf.put_u64_le(self.dir_offset);
+
+        // zip64 end of central directory locator
+        buf.put_u32_le(ZIP64_EOCD_LOCATOR_SIGNATURE);
+        // disk with the start of the zip64 eocd record
+        buf.put_u32_le(0);
+        // offset of the zip64 eocd record
+        buf.put_u64_le(zip64_eocd_offset);
+        // total number of disks
+        buf.put_u32_le(1);
+    }
+
     fn add_to_bytes<T: BufMut>(&self, buf: &mut T) -> Result<()> {
+        let zip64 = self.needs_zip64();
+        if zip64 {
+            self.add_zip64_to_bytes(buf);
+        }
+
         // signature
         buf.put_u32_le(CENTRAL_DIRECTORY_END_SIGNATURE);
         // disk number
         buf.put_u16_le(0);
         // disk with central directory
         buf.put_u16_le(0);
+        let number_of_files = if self.number_of_files > std::u16::MAX as u64 {
+            std::u16::MAX
+        } else {
+            self.number_of_files as u16
+        };
         //number of files on this disk
-        buf.put_u16_le(self.number_of_files);
+        buf.put_u16_le(number_of_files);
         // total number of files
-        buf.put_u16_le(self.number_of_files);
+        buf.put_u16_le(number_of_files);
         // directory size
-        buf.put_u32_le(self.dir_size);
+        buf.put_u32_le(if self.dir_size > std::u32::MAX as u64 {
+            std::u32::MAX
+        } else {
+            self.dir_size as u32
+        });
         // directory offset from start
-        if self.dir_offset > std::u32::MAX as u64 {
-            return Err(Error::ArchiveTooBig);
-        }
-        buf.put_u32_le(self.dir_offset as u32);
+        buf.put_u32_le(if self.dir_offset > std::u32::MAX as u64 {
+            std::u32::MAX
+        } else {
+            self.dir_offset as u32
+        });
         // Comment length
-        buf.put_u16_le(0);
+        if self.comment.len() > std::u16::MAX as usize {
+            return Err(Error::CommentTooBig);
+        }
+        buf.put_u16_le(self.comment.len() as u16);
         // Comment
-        //buf.put_all(&self.zip_file_comment);
+        buf.put_slice(&self.comment);
 
         Ok(())
     }
@@ -208,6 +442,9 @@ impl DirectoryEnd {
 pub struct Directory {
     entries: Vec<DirectoryEntry>,
     offset: Option<u64>,
+    /// Global archive comment, written into the end-of-central-directory
+    /// record.
+    comment: Option<String>,
 }
 
 impl Directory {
@@ -215,14 +452,21 @@ impl Directory {
         Directory {
             entries: Vec::new(),
             offset: None,
+            comment: None,
         }
     }
 
-    pub fn add_entry(&mut self, header: FileHeader, desc: Descriptor, offset: u64) {
+    /// Sets the archive-level comment stored in the EOCD record.
+    pub fn set_comment(&mut self, comment: impl Into<String>) {
+        self.comment = Some(comment.into());
+    }
+
+    pub fn add_entry(&mut self, header: FileHeader, desc: Descriptor, offset: u64, comment: Option<String>) {
         self.entries.push(DirectoryEntry {
             header,
             desc,
             offset,
+            comment,
         })
     }
 
@@ -234,25 +478,29 @@ impl Directory {
 
 impl ToBytes for Directory {
     fn to_bytes(&self) -> Result<Vec<u8>> {
-        let num_files = self.entries.len();
-        let cap = self.entries.iter().map(|e| e.size()).sum::<u32>() + 22;
+        let num_files = self.entries.len() as u64;
+        let comment = self.comment.as_deref().unwrap_or("").as_bytes().to_vec();
+        // leave extra room for the zip64 eocd record (56 bytes) + locator
+        // (20 bytes)
+        let cap = self.entries.iter().map(|e| e.size() as u64).sum::<u64>()
+            + 22
+            + comment.len() as u64
+            + 76;
         let mut d = BytesMut::with_capacity(cap as usize);
         for e in &self.entries {
             e.add_to_bytes(&mut d)?;
         }
 
-        let dir_size = d.len();
+        let dir_size = d.len() as u64;
 
         let offset = self
             .offset
             .expect("invalid state - must update offset first");
-        if offset > std::u32::MAX as u64 {
-            return Err(Error::ArchiveTooBig);
-        }
         let end = DirectoryEnd {
             dir_offset: offset,
-            dir_size: dir_size as u32,
-            number_of_files: num_files as u16,
+            dir_size,
+            number_of_files: num_files,
+            comment,
         };
 
         end.add_to_bytes(&mut d)?;
@@ -260,3 +508,55 @@ impl ToBytes for Directory {
         Ok(d.to_vec())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn file_header_reserves_zip64_extra_field_past_4gib() {
+        let huge = std::u32::MAX as u64 + 1;
+        let header = FileHeader::new("big.bin", SystemTime::now(), CompressionMethod::Store, huge, 0o644);
+        let bytes = header.to_bytes().unwrap();
+
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        assert_eq!(version, ZIP64_VERSION);
+        let extra_len = u16::from_le_bytes([bytes[28], bytes[29]]);
+        // zip64 extra field header (4 bytes) + two placeholder u64 values
+        assert_eq!(extra_len, 4 + 2 * 8);
+    }
+
+    #[test]
+    fn descriptor_uses_8_byte_fields_past_4gib() {
+        let huge = std::u32::MAX as u64 + 1;
+        let desc = Descriptor::new(huge, 100, 0xdead_beef);
+        let bytes = desc.to_bytes().unwrap();
+        // signature(4) + crc(4) + compressed(8) + uncompressed(8)
+        assert_eq!(bytes.len(), 24);
+    }
+
+    #[test]
+    fn descriptor_uses_4_byte_fields_when_small() {
+        let desc = Descriptor::new(100, 50, 0xdead_beef);
+        let bytes = desc.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 16);
+    }
+
+    #[test]
+    fn directory_reserves_zip64_eocd_for_offsets_past_4gib() {
+        let mut dir = Directory::new();
+        let header = FileHeader::new("big.bin", SystemTime::now(), CompressionMethod::Store, 10, 0o644);
+        let desc = Descriptor::new(10, 10, 0);
+        let big_offset = std::u32::MAX as u64 + 1;
+        dir.add_entry(header, desc, big_offset, None);
+        let bytes = dir.finalize(big_offset + 10).unwrap();
+
+        assert!(bytes
+            .windows(4)
+            .any(|w| w == ZIP64_EOCD_SIGNATURE.to_le_bytes()));
+        assert!(bytes
+            .windows(4)
+            .any(|w| w == ZIP64_EOCD_LOCATOR_SIGNATURE.to_le_bytes()));
+    }
+}